@@ -0,0 +1,716 @@
+//! The events a progress renderer receives, and their two wire protocols.
+//!
+//! The text protocol is one command per line (`set_total 10`, `msg Fetching`,
+//! `log ...`, `error ...`, `update`, `done`). A bare payload runs to the end of
+//! the line and loses its surrounding whitespace; one that would not survive
+//! that (empty, spanning lines, padded, or starting with `"`) is written as a
+//! JSON string instead, `msg "  two\nlines"`. Word fields such as the phase,
+//! result and deployment are bare when they hold no whitespace and quoted the
+//! same way otherwise. The JSON protocol is one flat object per line
+//! (`{"event":"msg","text":"Fetching"}`). Both carry every event losslessly.
+//!
+//! The phase events are what `--progress-json` emits for other programs: a
+//! `phase_started` for each phase (see [`crate::phase`] for the identifiers),
+//! `phase_progress` percentages where hammer knows them, `phase_completed`, and
+//! one `operation_result` last, naming the deployment whose transcript
+//! `hammer inspect --log` shows.
+//!
+//! The branch events are for phases hammer runs side by side (see
+//! core/src/parallel.cr): `branch_started <branch> <text>` and
+//! `branch_finished <branch> <done|failed> <text>`. A renderer draws one line
+//! per running branch beneath its bar.
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    SetTotal(u64),
+    Msg(String),
+    Log(String),
+    Error(String),
+    Update,
+    Done,
+    PhaseStarted {
+        phase: String,
+        text: String,
+    },
+    PhaseProgress {
+        phase: String,
+        percent: u64,
+    },
+    PhaseCompleted {
+        phase: String,
+    },
+    OperationResult {
+        result: String,
+        deployment: Option<String>,
+    },
+    BranchStarted {
+        branch: String,
+        text: String,
+    },
+    BranchFinished {
+        branch: String,
+        /// `done` or `failed`.
+        result: String,
+        text: String,
+    },
+}
+
+impl Event {
+    /// Parses a line of either protocol; lines starting with `{` are JSON.
+    ///
+    /// Unknown commands and malformed lines give `None`, so renderers can skip
+    /// them the way the text protocol always has.
+    pub fn parse(line: &str) -> Option<Event> {
+        let line = line.trim();
+        if line.starts_with('{') {
+            Event::parse_json(line).ok()
+        } else {
+            Event::parse_text(line)
+        }
+    }
+
+    pub fn parse_text(line: &str) -> Option<Event> {
+        let line = line.trim();
+        if let Some(total) = line.strip_prefix("set_total ") {
+            total.trim().parse().ok().map(Event::SetTotal)
+        } else if let Some(msg) = line.strip_prefix("msg ") {
+            Some(Event::Msg(text_payload(msg)))
+        } else if let Some(log) = line.strip_prefix("log ") {
+            Some(Event::Log(text_payload(log)))
+        } else if let Some(err) = line.strip_prefix("error ") {
+            Some(Event::Error(text_payload(err)))
+        } else if let Some(rest) = line.strip_prefix("phase_started ") {
+            let (phase, text) = take_word(rest)?;
+            Some(Event::PhaseStarted {
+                phase,
+                text: text_payload(&text),
+            })
+        } else if let Some(rest) = line.strip_prefix("phase_progress ") {
+            let (phase, rest) = take_word(rest)?;
+            let (percent, rest) = take_word(&rest)?;
+            if !rest.trim().is_empty() {
+                return None;
+            }
+            Some(Event::PhaseProgress {
+                phase,
+                percent: percent.parse().ok()?,
+            })
+        } else if let Some(rest) = line.strip_prefix("phase_completed ") {
+            let (phase, rest) = take_word(rest)?;
+            if !rest.trim().is_empty() {
+                return None;
+            }
+            Some(Event::PhaseCompleted { phase })
+        } else if let Some(rest) = line.strip_prefix("operation_result ") {
+            let (result, rest) = take_word(rest)?;
+            let deployment = if rest.trim().is_empty() {
+                None
+            } else {
+                let (deployment, rest) = take_word(&rest)?;
+                if !rest.trim().is_empty() {
+                    return None;
+                }
+                Some(deployment)
+            };
+            Some(Event::OperationResult { result, deployment })
+        } else if let Some(rest) = line.strip_prefix("branch_started ") {
+            let (branch, text) = take_word(rest)?;
+            Some(Event::BranchStarted {
+                branch,
+                text: text_payload(&text),
+            })
+        } else if let Some(rest) = line.strip_prefix("branch_finished ") {
+            let (branch, rest) = take_word(rest)?;
+            let (result, text) = take_word(&rest)?;
+            Some(Event::BranchFinished {
+                branch,
+                result,
+                text: text_payload(&text),
+            })
+        } else if line == "update" {
+            Some(Event::Update)
+        } else if line == "done" {
+            Some(Event::Done)
+        } else {
+            None
+        }
+    }
+
+    /// The text protocol line, without the trailing newline.
+    pub fn to_text(&self) -> String {
+        match self {
+            Event::SetTotal(total) => format!("set_total {total}"),
+            Event::Msg(msg) => format!("msg {}", payload_field(msg)),
+            Event::Log(log) => format!("log {}", payload_field(log)),
+            Event::Error(err) => format!("error {}", payload_field(err)),
+            Event::Update => "update".to_string(),
+            Event::Done => "done".to_string(),
+            // An empty description is left out, as older emitters do
+            Event::PhaseStarted { phase, text } if text.is_empty() => {
+                format!("phase_started {}", word_field(phase))
+            }
+            Event::PhaseStarted { phase, text } => {
+                format!(
+                    "phase_started {} {}",
+                    word_field(phase),
+                    payload_field(text)
+                )
+            }
+            Event::PhaseProgress { phase, percent } => {
+                format!("phase_progress {} {percent}", word_field(phase))
+            }
+            Event::PhaseCompleted { phase } => format!("phase_completed {}", word_field(phase)),
+            Event::OperationResult { result, deployment } => match deployment {
+                Some(deployment) => format!(
+                    "operation_result {} {}",
+                    word_field(result),
+                    word_field(deployment)
+                ),
+                None => format!("operation_result {}", word_field(result)),
+            },
+            Event::BranchStarted { branch, text } if text.is_empty() => {
+                format!("branch_started {}", word_field(branch))
+            }
+            Event::BranchStarted { branch, text } => {
+                format!(
+                    "branch_started {} {}",
+                    word_field(branch),
+                    payload_field(text)
+                )
+            }
+            Event::BranchFinished {
+                branch,
+                result,
+                text,
+            } if text.is_empty() => {
+                format!(
+                    "branch_finished {} {}",
+                    word_field(branch),
+                    word_field(result)
+                )
+            }
+            Event::BranchFinished {
+                branch,
+                result,
+                text,
+            } => format!(
+                "branch_finished {} {} {}",
+                word_field(branch),
+                word_field(result),
+                payload_field(text)
+            ),
+        }
+    }
+
+    pub fn parse_json(line: &str) -> Result<Event, String> {
+        let fields = parse_flat_object(line)?;
+        let get = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+        let text = |key: &str| match get(key) {
+            Some(Value::Str(s)) => Ok(s.clone()),
+            _ => Err(format!("Missing string field '{key}'")),
+        };
+        match get("event") {
+            Some(Value::Str(event)) => match event.as_str() {
+                "set_total" => match get("total") {
+                    Some(Value::Num(total)) => Ok(Event::SetTotal(*total)),
+                    _ => Err("Missing number field 'total'".to_string()),
+                },
+                "msg" => text("text").map(Event::Msg),
+                "log" => text("text").map(Event::Log),
+                "error" => text("text").map(Event::Error),
+                "update" => Ok(Event::Update),
+                "done" => Ok(Event::Done),
+                "phase_started" => Ok(Event::PhaseStarted {
+                    phase: text("phase")?,
+                    // Older emitters may leave the description out
+                    text: text("text").unwrap_or_default(),
+                }),
+                "phase_progress" => match get("percent") {
+                    Some(Value::Num(percent)) => Ok(Event::PhaseProgress {
+                        phase: text("phase")?,
+                        percent: *percent,
+                    }),
+                    _ => Err("Missing number field 'percent'".to_string()),
+                },
+                "phase_completed" => text("phase").map(|phase| Event::PhaseCompleted { phase }),
+                "operation_result" => Ok(Event::OperationResult {
+                    result: text("result")?,
+                    deployment: text("deployment").ok(),
+                }),
+                "branch_started" => Ok(Event::BranchStarted {
+                    branch: text("branch")?,
+                    text: text("text").unwrap_or_default(),
+                }),
+                "branch_finished" => Ok(Event::BranchFinished {
+                    branch: text("branch")?,
+                    result: text("result")?,
+                    text: text("text").unwrap_or_default(),
+                }),
+                other => Err(format!("Unknown event '{other}'")),
+            },
+            _ => Err("Missing string field 'event'".to_string()),
+        }
+    }
+
+    /// The JSON protocol line, without the trailing newline.
+    pub fn to_json(&self) -> String {
+        match self {
+            Event::SetTotal(total) => format!("{{\"event\":\"set_total\",\"total\":{total}}}"),
+            Event::Msg(text) => json_text("msg", text),
+            Event::Log(text) => json_text("log", text),
+            Event::Error(text) => json_text("error", text),
+            Event::Update => "{\"event\":\"update\"}".to_string(),
+            Event::Done => "{\"event\":\"done\"}".to_string(),
+            Event::PhaseStarted { phase, text } => format!(
+                "{{\"event\":\"phase_started\",\"phase\":{},\"text\":{}}}",
+                json_string(phase),
+                json_string(text)
+            ),
+            Event::PhaseProgress { phase, percent } => format!(
+                "{{\"event\":\"phase_progress\",\"phase\":{},\"percent\":{percent}}}",
+                json_string(phase)
+            ),
+            Event::PhaseCompleted { phase } => format!(
+                "{{\"event\":\"phase_completed\",\"phase\":{}}}",
+                json_string(phase)
+            ),
+            Event::OperationResult { result, deployment } => match deployment {
+                Some(deployment) => format!(
+                    "{{\"event\":\"operation_result\",\"result\":{},\"deployment\":{}}}",
+                    json_string(result),
+                    json_string(deployment)
+                ),
+                None => format!(
+                    "{{\"event\":\"operation_result\",\"result\":{}}}",
+                    json_string(result)
+                ),
+            },
+            Event::BranchStarted { branch, text } => format!(
+                "{{\"event\":\"branch_started\",\"branch\":{},\"text\":{}}}",
+                json_string(branch),
+                json_string(text)
+            ),
+            Event::BranchFinished {
+                branch,
+                result,
+                text,
+            } => format!(
+                "{{\"event\":\"branch_finished\",\"branch\":{},\"result\":{},\"text\":{}}}",
+                json_string(branch),
+                json_string(result),
+                json_string(text)
+            ),
+        }
+    }
+}
+
+/// A rest-of-line field of the text protocol, bare when reading it back gives s again.
+fn payload_field(s: &str) -> String {
+    if s.is_empty() || s.starts_with('"') || s.trim() != s || s.chars().any(char::is_control) {
+        json_string(s)
+    } else {
+        s.to_string()
+    }
+}
+
+/// A word field of the text protocol, bare when it is one word.
+fn word_field(s: &str) -> String {
+    if s.is_empty() || s.starts_with('"') || s.chars().any(|c| c.is_whitespace() || c.is_control())
+    {
+        json_string(s)
+    } else {
+        s.to_string()
+    }
+}
+
+/// A rest-of-line field: a JSON string when it is exactly one, the trimmed text otherwise.
+fn text_payload(input: &str) -> String {
+    let input = input.trim();
+    if input.starts_with('"') {
+        let mut chars = input.chars().peekable();
+        if let Ok(text) = parse_string(&mut chars) {
+            if chars.all(char::is_whitespace) {
+                return text;
+            }
+        }
+    }
+    input.to_string()
+}
+
+/// The first word of input, unquoted, and what follows it.
+fn take_word(input: &str) -> Option<(String, String)> {
+    let input = input.trim_start();
+    if input.starts_with('"') {
+        let mut chars = input.chars().peekable();
+        let word = parse_string(&mut chars).ok()?;
+        let rest: String = chars.collect();
+        // A quoted word ends at whitespace like a bare one
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        return Some((word, rest));
+    }
+    let end = input.find(char::is_whitespace).unwrap_or(input.len());
+    if end == 0 {
+        return None;
+    }
+    Some((input[..end].to_string(), input[end..].to_string()))
+}
+
+fn json_text(event: &str, text: &str) -> String {
+    format!("{{\"event\":\"{event}\",\"text\":{}}}", json_string(text))
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+enum Value {
+    Str(String),
+    Num(u64),
+}
+
+/// Parses an object whose values are strings or unsigned integers, which is all
+/// the protocol needs.
+fn parse_flat_object(input: &str) -> Result<Vec<(String, Value)>, String> {
+    let mut chars = input.trim().chars().peekable();
+    let mut fields = Vec::new();
+    expect(&mut chars, '{')?;
+    skip_ws(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_ws(&mut chars);
+            let key = parse_string(&mut chars)?;
+            skip_ws(&mut chars);
+            expect(&mut chars, ':')?;
+            skip_ws(&mut chars);
+            let value = match chars.peek() {
+                Some('"') => Value::Str(parse_string(&mut chars)?),
+                Some(c) if c.is_ascii_digit() => {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                        digits.push(*c);
+                        chars.next();
+                    }
+                    Value::Num(
+                        digits
+                            .parse()
+                            .map_err(|_| format!("Number out of range: {digits}"))?,
+                    )
+                }
+                _ => return Err(format!("Unsupported value for '{key}'")),
+            };
+            fields.push((key, value));
+            skip_ws(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err("Expected ',' or '}'".to_string()),
+            }
+        }
+    }
+    skip_ws(&mut chars);
+    if chars.next().is_some() {
+        return Err("Trailing characters after object".to_string());
+    }
+    Ok(fields)
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn skip_ws(chars: &mut Chars) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Chars, want: char) -> Result<(), String> {
+    match chars.next() {
+        Some(c) if c == want => Ok(()),
+        _ => Err(format!("Expected '{want}'")),
+    }
+}
+
+fn parse_string(chars: &mut Chars) -> Result<String, String> {
+    expect(chars, '"')?;
+    let mut out = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(out),
+            Some('\\') => match chars.next() {
+                Some('"') => out.push('"'),
+                Some('\\') => out.push('\\'),
+                Some('/') => out.push('/'),
+                Some('b') => out.push('\u{8}'),
+                Some('f') => out.push('\u{c}'),
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some('t') => out.push('\t'),
+                Some('u') => {
+                    let high = parse_hex4(chars)?;
+                    let code = if (0xD800..0xDC00).contains(&high) {
+                        // A surrogate pair spells one character outside the BMP
+                        if chars.next() != Some('\\') || chars.next() != Some('u') {
+                            return Err("Unpaired surrogate in string".to_string());
+                        }
+                        let low = parse_hex4(chars)?;
+                        if !(0xDC00..0xE000).contains(&low) {
+                            return Err("Unpaired surrogate in string".to_string());
+                        }
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    } else {
+                        high
+                    };
+                    out.push(char::from_u32(code).ok_or("Invalid escape in string")?);
+                }
+                _ => return Err("Invalid escape in string".to_string()),
+            },
+            Some(c) => out.push(c),
+            None => return Err("Unterminated string".to_string()),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Chars) -> Result<u32, String> {
+    let hex: String = chars.take(4).collect();
+    if hex.len() != 4 {
+        return Err("Truncated \\u escape".to_string());
+    }
+    u32::from_str_radix(&hex, 16).map_err(|_| format!("Invalid \\u escape: {hex}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts() -> Vec<String> {
+        [
+            "Fetching",
+            "Running apt upgrade",
+            "",
+            " padded ",
+            "two\nlines",
+            "tab\tinside",
+            "\"quoted\" start",
+            "ends with \"quote\"",
+            "back\\slash",
+            "unicode: zażółć 🦀",
+            "control \u{1}\u{7f}",
+            "{\"looks\":\"like json\"}",
+            "carriage\r\nreturn",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+
+    fn events() -> Vec<Event> {
+        let mut events = vec![
+            Event::SetTotal(0),
+            Event::SetTotal(u64::MAX),
+            Event::Update,
+            Event::Done,
+        ];
+        for text in texts() {
+            events.push(Event::Msg(text.clone()));
+            events.push(Event::Log(text.clone()));
+            events.push(Event::Error(text.clone()));
+            events.push(Event::PhaseStarted {
+                phase: "apt".to_string(),
+                text: text.clone(),
+            });
+            events.push(Event::PhaseStarted {
+                phase: text.clone(),
+                text: "Running apt".to_string(),
+            });
+            events.push(Event::PhaseProgress {
+                phase: text.clone(),
+                percent: 42,
+            });
+            events.push(Event::PhaseCompleted {
+                phase: text.clone(),
+            });
+            events.push(Event::OperationResult {
+                result: text.clone(),
+                deployment: None,
+            });
+            events.push(Event::OperationResult {
+                result: text.clone(),
+                deployment: Some("hammer-20240101120000".to_string()),
+            });
+            events.push(Event::OperationResult {
+                result: "success".to_string(),
+                deployment: Some(text.clone()),
+            });
+            events.push(Event::BranchStarted {
+                branch: text.clone(),
+                text: text.clone(),
+            });
+            events.push(Event::BranchFinished {
+                branch: "apt lists".to_string(),
+                result: text.clone(),
+                text: text.clone(),
+            });
+            events.push(Event::BranchFinished {
+                branch: "snapshot".to_string(),
+                result: "failed".to_string(),
+                text: text.clone(),
+            });
+        }
+        events
+    }
+
+    #[test]
+    fn every_event_round_trips_through_the_text_protocol() {
+        for event in events() {
+            let line = event.to_text();
+            assert!(!line.contains('\n'), "{line:?} spans lines");
+            assert_eq!(Event::parse_text(&line), Some(event.clone()), "{line:?}");
+            assert_eq!(Event::parse(&line), Some(event), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn every_event_round_trips_through_the_json_protocol() {
+        for event in events() {
+            let line = event.to_json();
+            assert!(!line.contains('\n'), "{line:?} spans lines");
+            assert_eq!(Event::parse_json(&line), Ok(event.clone()), "{line:?}");
+            assert_eq!(Event::parse(&line), Some(event), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn multi_word_results_are_quoted() {
+        let event = Event::OperationResult {
+            result: "rolled back".to_string(),
+            deployment: Some("hammer-20240101120000".to_string()),
+        };
+        assert_eq!(
+            event.to_text(),
+            "operation_result \"rolled back\" hammer-20240101120000"
+        );
+        assert_eq!(
+            event.to_json(),
+            "{\"event\":\"operation_result\",\"result\":\"rolled back\",\"deployment\":\"hammer-20240101120000\"}"
+        );
+    }
+
+    #[test]
+    fn operation_result_without_deployment() {
+        let event = Event::OperationResult {
+            result: "failed".to_string(),
+            deployment: None,
+        };
+        assert_eq!(event.to_text(), "operation_result failed");
+        assert_eq!(
+            event.to_json(),
+            "{\"event\":\"operation_result\",\"result\":\"failed\"}"
+        );
+        assert_eq!(
+            Event::parse("operation_result failed   "),
+            Some(event.clone())
+        );
+        assert_eq!(
+            Event::parse("{\"event\":\"operation_result\",\"result\":\"failed\"}"),
+            Some(event)
+        );
+    }
+
+    #[test]
+    fn plain_payloads_stay_bare() {
+        assert_eq!(
+            Event::Msg("Running apt".to_string()).to_text(),
+            "msg Running apt"
+        );
+        assert_eq!(
+            Event::PhaseStarted {
+                phase: "apt".to_string(),
+                text: String::new(),
+            }
+            .to_text(),
+            "phase_started apt"
+        );
+        assert_eq!(Event::Msg(" x".to_string()).to_text(), "msg \" x\"");
+    }
+
+    #[test]
+    fn hand_written_text_lines_still_parse() {
+        assert_eq!(
+            Event::parse("  msg   Fetching lists  "),
+            Some(Event::Msg("Fetching lists".to_string()))
+        );
+        assert_eq!(Event::parse("set_total 7"), Some(Event::SetTotal(7)));
+        assert_eq!(
+            Event::parse("phase_started apt Running apt"),
+            Some(Event::PhaseStarted {
+                phase: "apt".to_string(),
+                text: "Running apt".to_string(),
+            })
+        );
+        // Not a single JSON string, so the quote is part of the text
+        assert_eq!(
+            Event::parse("log \"a\" and b"),
+            Some(Event::Log("\"a\" and b".to_string()))
+        );
+        assert_eq!(
+            Event::parse("operation_result staged hammer-1"),
+            Some(Event::OperationResult {
+                result: "staged".to_string(),
+                deployment: Some("hammer-1".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        for line in [
+            "",
+            "frobnicate",
+            "set_total many",
+            "phase_progress apt",
+            "phase_progress apt lots",
+            "phase_progress apt 5 extra",
+            "phase_completed apt extra",
+            "operation_result",
+            "operation_result a b c",
+            "operation_result \"unterminated",
+            "operation_result \"glued\"word",
+            "{\"event\":\"msg\"}",
+            "{\"event\":\"nope\"}",
+            "{\"event\":\"set_total\",\"total\":-1}",
+            "{\"event\":\"msg\",\"text\":\"x\"} trailing",
+        ] {
+            assert_eq!(Event::parse(line), None, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn json_escapes_outside_the_basic_plane() {
+        assert_eq!(
+            Event::parse_json("{\"event\":\"msg\",\"text\":\"\\ud83e\\udd80 \\u00e9\"}"),
+            Ok(Event::Msg("🦀 é".to_string()))
+        );
+        assert!(Event::parse_json("{\"event\":\"msg\",\"text\":\"\\ud83e\"}").is_err());
+    }
+}
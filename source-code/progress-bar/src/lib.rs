@@ -0,0 +1,48 @@
+//! Progress rendering for hammer: the [`Event`]s a renderer understands, their
+//! text and JSON line protocols, the stable [`phase`] identifiers hammer
+//! reports, an indicatif-backed [`ProgressSink`], and the [`Summary`] of a run.
+//! The `hammer-progress-bar` binary reads events from stdin and feeds them to
+//! an [`IndicatifSink`].
+
+pub mod event;
+pub mod phase;
+pub mod sink;
+pub mod summary;
+
+pub use event::Event;
+pub use sink::{IndicatifSink, Options, ProgressSink};
+pub use summary::{Summary, Termination};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps what it is handed, as an embedding renderer would draw it.
+    #[derive(Default)]
+    struct Recorder(Vec<Event>);
+
+    impl ProgressSink for Recorder {
+        fn handle(&mut self, event: &Event) {
+            self.0.push(event.clone());
+        }
+    }
+
+    #[test]
+    fn a_mixed_stream_reaches_any_sink() {
+        let input = "set_total 2\n{\"event\":\"msg\",\"text\":\"Fetching\"}\nnoise\nupdate\nerror \"two\\nlines\"\ndone\n";
+        let mut sink = Recorder::default();
+        for event in input.lines().filter_map(Event::parse) {
+            sink.handle(&event);
+        }
+        assert_eq!(
+            sink.0,
+            vec![
+                Event::SetTotal(2),
+                Event::Msg("Fetching".to_string()),
+                Event::Update,
+                Event::Error("two\nlines".to_string()),
+                Event::Done,
+            ]
+        );
+    }
+}
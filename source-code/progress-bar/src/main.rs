@@ -1,64 +1,89 @@
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::io::{self, BufRead};
-use std::time::{Duration, Instant};
+use hammer_progress_bar::{Event, IndicatifSink, Options, ProgressSink, Summary};
+use std::io;
+use std::process;
 
-fn main() {
-    let start_time = Instant::now();
-    let stdin = io::stdin();
-    let mut total: u64 = 0;
-    let mut current: u64 = 0;
-    let mut message = String::from("Initializing...");
+const USAGE: &str = "Usage: hammer-progress-bar [--width N] [--style <template>] [--chars <chars>] [--output <path>] [--summary-file <path>]";
+
+/// The command line: how to draw, and where to leave the summary of the run.
+struct Args {
+    opts: Options,
+    summary_file: Option<String>,
+}
 
-    let m = MultiProgress::new();
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut opts = Options::default();
+    let mut summary_file = None;
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        if flag == "--help" || flag == "-h" {
+            println!("{USAGE}");
+            process::exit(0);
+        }
+        let value = match inline.or_else(|| args.next()) {
+            Some(v) => v,
+            None => return Err(format!("Missing value for {flag}")),
+        };
+        match flag.as_str() {
+            "--width" => match value.parse::<u16>() {
+                Ok(w) if w > 0 => opts.width = Some(w),
+                _ => {
+                    return Err(format!(
+                        "Invalid width '{value}': expected a positive number of columns"
+                    ))
+                }
+            },
+            "--style" => opts.style = Some(value),
+            "--chars" => opts.chars = Some(value),
+            "--output" => opts.output = Some(value),
+            "--summary-file" => summary_file = Some(value),
+            _ => return Err(format!("Unknown option: {flag}")),
+        }
+    }
+    Ok(Args { opts, summary_file })
+}
 
-    let pb = m.add(ProgressBar::new(total));
-    pb.set_style(
-        ProgressStyle::with_template(
-            "{spinner} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg} ETA: {eta_precise}"
-        )
-        .unwrap()
-        .progress_chars("█▌ ")
-        .tick_strings(&["▁", "▂", "▃", "▄", "▅", "▆", "▇", "█", "▇", "▆", "▅", "▄", "▃", "▁", ""])
-    );
-    pb.enable_steady_tick(Duration::from_millis(100));
-    pb.set_message(message.clone());
+fn main() {
+    let Args { opts, summary_file } = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {e}\n{USAGE}");
+            process::exit(2);
+        }
+    };
+    let mut sink = match IndicatifSink::new(&opts) {
+        Ok(sink) => sink,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            process::exit(2);
+        }
+    };
 
-    let log_pb = m.add(ProgressBar::new(0));
-    log_pb.set_style(
-        ProgressStyle::with_template("{msg}")
-            .unwrap()
-    );
-    log_pb.set_message("No logs yet...");
+    let mut summary = Summary::new();
 
-    for line in stdin.lines() {
-        let line = match line {
-            Ok(l) => l.trim().to_string(),
-            Err(_) => break,
-        };
-        if line.is_empty() {
+    // Each line is a text or JSON protocol event; anything else is skipped
+    for line in io::stdin().lines() {
+        let Ok(line) = line else { break };
+        let Some(event) = Event::parse(&line) else {
             continue;
-        }
-        if line.starts_with("set_total ") {
-            if let Ok(t) = line[10..].parse::<u64>() {
-                total = t;
-                pb.set_length(total);
-            }
-        } else if line.starts_with("msg ") {
-            message = line[4..].to_string();
-            pb.set_message(message.clone());
-        } else if line.starts_with("log ") {
-            let log_msg = format!("Log: {}", &line[4..]);
-            log_pb.set_message(log_msg);
-        } else if line.starts_with("error ") {
-            let err_msg = format!("Error: {}", &line[6..]);
-            log_pb.set_message(err_msg);
-        } else if line == "update" {
-            current += 1;
-            pb.set_position(current);
-        } else if line == "done" {
-            pb.finish_with_message(format!("Completed in {:.2}s", start_time.elapsed().as_secs_f64()));
-            log_pb.finish_and_clear();
+        };
+        sink.handle(&event);
+        summary.observe(&event);
+        if summary.termination().is_some() {
             break;
         }
     }
+    summary.interrupt();
+
+    if let Some(path) = summary_file {
+        if let Err(e) = summary.write(&path) {
+            eprintln!("Error: {e}");
+            process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,69 @@
+//! Stable identifiers of the phases hammer operations report.
+//!
+//! They appear as the `phase` field of [`Event::PhaseStarted`],
+//! [`Event::PhaseProgress`] and [`Event::PhaseCompleted`], and are part of the
+//! `--progress-json` interface: new phases may be added, existing identifiers
+//! are never renamed.
+//!
+//! [`Event::PhaseStarted`]: crate::Event::PhaseStarted
+//! [`Event::PhaseProgress`]: crate::Event::PhaseProgress
+//! [`Event::PhaseCompleted`]: crate::Event::PhaseCompleted
+
+/// Snapshotting the source deployment (install, remove).
+pub const CREATE_DEPLOYMENT: &str = "create_deployment";
+/// apt inside the new deployment; the only phase with `phase_progress` events.
+pub const APT: &str = "apt";
+/// initramfs and grub configuration (install, remove).
+pub const BOOT_FILES: &str = "boot_files";
+/// Metadata, identity files, boot entries and read-only seal.
+pub const FINALIZE: &str = "finalize";
+/// debootstrap of an empty deployment (compose).
+pub const BOOTSTRAP: &str = "bootstrap";
+/// The recipe's package list (compose).
+pub const PACKAGES: &str = "packages";
+/// Copying the recipe's overlay directory (compose).
+pub const OVERLAY: &str = "overlay";
+/// One recipe hook (compose); reported once per hook.
+pub const HOOK: &str = "hook";
+
+/// Every identifier above.
+pub const ALL: &[&str] = &[
+    CREATE_DEPLOYMENT,
+    APT,
+    BOOT_FILES,
+    FINALIZE,
+    BOOTSTRAP,
+    PACKAGES,
+    OVERLAY,
+    HOOK,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_are_unique_snake_case_words() {
+        for (i, phase) in ALL.iter().enumerate() {
+            assert!(!phase.is_empty());
+            assert!(
+                phase.chars().all(|c| c.is_ascii_lowercase() || c == '_'),
+                "{phase}"
+            );
+            assert!(!ALL[i + 1..].contains(phase), "{phase} is listed twice");
+        }
+    }
+
+    /// hammer-core emits these from its own constants, which must name the same phases.
+    #[test]
+    fn hammer_core_uses_the_same_identifiers() {
+        let progress = include_str!("../../core/src/progress.cr");
+        let core: Vec<&str> = progress
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("PHASE_"))
+            .filter_map(|line| line.split_once(" = "))
+            .map(|(_, value)| value.trim_matches('"'))
+            .collect();
+        assert_eq!(core, ALL);
+    }
+}
@@ -0,0 +1,567 @@
+//! Rendering events with indicatif, behind the [`ProgressSink`] trait.
+
+use crate::event::Event;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle, TermLike};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_TEMPLATE: &str =
+    "{spinner} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg} ETA: {eta_precise}";
+const WIDE_TEMPLATE: &str =
+    "{spinner} [{elapsed_precise}] {wide_bar:.cyan/blue} {pos:>7}/{len:7} {msg} ETA: {eta_precise}";
+const DEFAULT_CHARS: &str = "█▌ ";
+/// A branch while it runs, and once it ended.
+const BRANCH_TEMPLATE: &str = "  {spinner} {prefix}: {msg} [{elapsed}]";
+const BRANCH_FINISHED_TEMPLATE: &str = "  {msg}";
+/// Width used when rendering somewhere the terminal width can't be detected.
+const FALLBACK_WIDTH: u16 = 80;
+/// Placeholders understood by indicatif templates.
+const PLACEHOLDERS: &[&str] = &[
+    "bar",
+    "wide_bar",
+    "spinner",
+    "prefix",
+    "wide_prefix",
+    "msg",
+    "wide_msg",
+    "pos",
+    "human_pos",
+    "len",
+    "human_len",
+    "percent",
+    "percent_precise",
+    "bytes",
+    "binary_bytes",
+    "decimal_bytes",
+    "total_bytes",
+    "binary_total_bytes",
+    "decimal_total_bytes",
+    "bytes_per_sec",
+    "binary_bytes_per_sec",
+    "decimal_bytes_per_sec",
+    "elapsed",
+    "elapsed_precise",
+    "per_sec",
+    "eta",
+    "eta_precise",
+    "duration",
+    "duration_precise",
+];
+/// How the bar looks and where it is drawn.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Fixed terminal width in columns, detected when unset.
+    pub width: Option<u16>,
+    /// indicatif template replacing the default one.
+    pub style: Option<String>,
+    /// Progress characters, filled to empty.
+    pub chars: Option<String>,
+    /// File or FIFO to draw into instead of stderr.
+    pub output: Option<String>,
+}
+
+/// Checks every `{key}` / `{key:fmt}` placeholder against the keys indicatif knows about.
+fn validate_placeholders(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix('{') {
+            rest = escaped;
+            continue;
+        }
+        let end = after
+            .find('}')
+            .ok_or_else(|| format!("Unclosed placeholder in template: {template}"))?;
+        let key = after[..end].split(':').next().unwrap_or("");
+        if !PLACEHOLDERS.contains(&key) {
+            return Err(format!("Unknown placeholder '{{{key}}}' in template"));
+        }
+        rest = &after[end + 1..];
+    }
+    Ok(())
+}
+
+pub fn build_style(opts: &Options) -> Result<ProgressStyle, String> {
+    let template = match (&opts.style, opts.width) {
+        (Some(style), _) => style.as_str(),
+        (None, Some(_)) => WIDE_TEMPLATE,
+        (None, None) => DEFAULT_TEMPLATE,
+    };
+    let placeholders = PLACEHOLDERS.join(", ");
+    validate_placeholders(template).map_err(|e| {
+        format!("Invalid --style template: {e}\nAllowed placeholders: {placeholders}")
+    })?;
+    let style = ProgressStyle::with_template(template).map_err(|e| {
+        format!("Invalid --style template: {e}\nAllowed placeholders: {placeholders}")
+    })?;
+    let chars = opts.chars.as_deref().unwrap_or(DEFAULT_CHARS);
+    validate_chars(chars)?;
+    Ok(style.progress_chars(chars).tick_strings(&[
+        "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█", "▇", "▆", "▅", "▄", "▃", "▁", "",
+    ]))
+}
+
+/// indicatif panics on fewer than two progress chars or chars of differing width.
+fn validate_chars(chars: &str) -> Result<(), String> {
+    let mut widths = chars
+        .chars()
+        .map(|c| console::measure_text_width(c.encode_utf8(&mut [0; 4])));
+    let first = widths.next();
+    if chars.chars().count() < 2 {
+        return Err(format!(
+            "Invalid --chars '{chars}': at least 2 characters are required"
+        ));
+    }
+    if widths.any(|w| Some(w) != first) {
+        return Err(format!(
+            "Invalid --chars '{chars}': all characters must have the same display width"
+        ));
+    }
+    Ok(())
+}
+
+/// Terminal-like sink that writes bar redraws as ANSI sequences to a file or FIFO.
+struct OutputTerm {
+    out: Mutex<File>,
+    width: u16,
+}
+
+impl fmt::Debug for OutputTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutputTerm")
+            .field("width", &self.width)
+            .finish()
+    }
+}
+
+impl OutputTerm {
+    fn write(&self, s: &str) -> io::Result<()> {
+        self.out
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .write_all(s.as_bytes())
+    }
+}
+
+impl TermLike for OutputTerm {
+    fn width(&self) -> u16 {
+        self.width
+    }
+
+    fn move_cursor_up(&self, n: usize) -> io::Result<()> {
+        if n > 0 {
+            self.write(&format!("\x1b[{n}A"))?;
+        }
+        Ok(())
+    }
+
+    fn move_cursor_down(&self, n: usize) -> io::Result<()> {
+        if n > 0 {
+            self.write(&format!("\x1b[{n}B"))?;
+        }
+        Ok(())
+    }
+
+    fn move_cursor_right(&self, n: usize) -> io::Result<()> {
+        if n > 0 {
+            self.write(&format!("\x1b[{n}C"))?;
+        }
+        Ok(())
+    }
+
+    fn move_cursor_left(&self, n: usize) -> io::Result<()> {
+        if n > 0 {
+            self.write(&format!("\x1b[{n}D"))?;
+        }
+        Ok(())
+    }
+
+    fn write_line(&self, s: &str) -> io::Result<()> {
+        self.write(&format!("{s}\n"))
+    }
+
+    fn write_str(&self, s: &str) -> io::Result<()> {
+        self.write(s)
+    }
+
+    fn clear_line(&self) -> io::Result<()> {
+        self.write("\r\x1b[2K")
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.out.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+/// The stderr terminal with a fixed width, for `--width` without `--output`.
+#[derive(Debug)]
+struct SizedTerm {
+    term: console::Term,
+    width: u16,
+}
+
+impl TermLike for SizedTerm {
+    fn width(&self) -> u16 {
+        self.width
+    }
+
+    fn move_cursor_up(&self, n: usize) -> io::Result<()> {
+        self.term.move_cursor_up(n)
+    }
+
+    fn move_cursor_down(&self, n: usize) -> io::Result<()> {
+        self.term.move_cursor_down(n)
+    }
+
+    fn move_cursor_right(&self, n: usize) -> io::Result<()> {
+        self.term.move_cursor_right(n)
+    }
+
+    fn move_cursor_left(&self, n: usize) -> io::Result<()> {
+        self.term.move_cursor_left(n)
+    }
+
+    fn write_line(&self, s: &str) -> io::Result<()> {
+        self.term.write_line(s)
+    }
+
+    fn write_str(&self, s: &str) -> io::Result<()> {
+        self.term.write_str(s)
+    }
+
+    fn clear_line(&self) -> io::Result<()> {
+        self.term.clear_line()
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.term.flush()
+    }
+}
+
+pub fn build_target(opts: &Options) -> Result<ProgressDrawTarget, String> {
+    if let Some(path) = &opts.output {
+        // Opening a FIFO for writing blocks until a reader attaches, which is what we want.
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Cannot open output {path}: {e}"))?;
+        let width = opts
+            .width
+            .or_else(|| console::Term::stderr().size_checked().map(|(_, cols)| cols))
+            .unwrap_or(FALLBACK_WIDTH);
+        return Ok(ProgressDrawTarget::term_like(Box::new(OutputTerm {
+            out: Mutex::new(file),
+            width,
+        })));
+    }
+    match opts.width {
+        Some(width) => Ok(ProgressDrawTarget::term_like(Box::new(SizedTerm {
+            term: console::Term::stderr(),
+            width,
+        }))),
+        None => Ok(ProgressDrawTarget::stderr()),
+    }
+}
+
+/// Something that presents progress events.
+pub trait ProgressSink {
+    fn handle(&mut self, event: &Event);
+}
+
+/// A bar, a line per running branch and a log line beneath them, drawn by indicatif.
+pub struct IndicatifSink {
+    bar: ProgressBar,
+    log: ProgressBar,
+    // Keeps every line drawn on the shared target
+    multi: MultiProgress,
+    branches: Vec<(String, ProgressBar)>,
+    started: Instant,
+    position: u64,
+}
+
+impl IndicatifSink {
+    pub fn new(opts: &Options) -> Result<IndicatifSink, String> {
+        let style = build_style(opts)?;
+        let multi = MultiProgress::with_draw_target(build_target(opts)?);
+
+        let bar = multi.add(ProgressBar::new(0));
+        bar.set_style(style);
+        bar.enable_steady_tick(Duration::from_millis(100));
+        bar.set_message("Initializing...");
+
+        let log = multi.add(ProgressBar::new(0));
+        log.set_style(ProgressStyle::with_template("{msg}").expect("static template is valid"));
+        log.set_message("No logs yet...");
+
+        Ok(IndicatifSink {
+            bar,
+            log,
+            multi,
+            branches: Vec::new(),
+            started: Instant::now(),
+            position: 0,
+        })
+    }
+
+    fn start_branch(&mut self, branch: &str, text: &str) {
+        let line = match self.branches.iter().find(|(name, _)| name == branch) {
+            Some((_, line)) => line.clone(),
+            None => {
+                // Between the bar and the log line, in the order the branches started
+                let line = self
+                    .multi
+                    .insert_before(&self.log, ProgressBar::new_spinner());
+                line.set_style(
+                    ProgressStyle::with_template(BRANCH_TEMPLATE)
+                        .expect("static template is valid"),
+                );
+                line.set_prefix(branch.to_string());
+                line.enable_steady_tick(Duration::from_millis(100));
+                self.branches.push((branch.to_string(), line.clone()));
+                line
+            }
+        };
+        line.set_message(if text.is_empty() { "running" } else { text }.to_string());
+    }
+
+    fn finish_branch(&mut self, branch: &str, result: &str, text: &str) {
+        if !self.branches.iter().any(|(name, _)| name == branch) {
+            self.start_branch(branch, "");
+        }
+        let Some((_, line)) = self.branches.iter().find(|(name, _)| name == branch) else {
+            return;
+        };
+        let mark = if result == "failed" { "✗" } else { "✓" };
+        line.set_style(
+            ProgressStyle::with_template(BRANCH_FINISHED_TEMPLATE)
+                .expect("static template is valid"),
+        );
+        line.finish_with_message(if text.is_empty() {
+            format!("{mark} {branch}: {result}")
+        } else {
+            format!("{mark} {branch}: {result} {text}")
+        });
+    }
+}
+
+impl ProgressSink for IndicatifSink {
+    fn handle(&mut self, event: &Event) {
+        match event {
+            Event::SetTotal(total) => self.bar.set_length(*total),
+            Event::Msg(msg) => self.bar.set_message(msg.clone()),
+            Event::Log(log) => self.log.set_message(format!("Log: {log}")),
+            Event::Error(err) => self.log.set_message(format!("Error: {err}")),
+            Event::Update => {
+                self.position += 1;
+                self.bar.set_position(self.position);
+            }
+            Event::Done | Event::OperationResult { .. } => {
+                // Branches still running when the operation ended did not get to finish
+                for (_, line) in &self.branches {
+                    if !line.is_finished() {
+                        line.abandon();
+                    }
+                }
+                self.bar.finish_with_message(format!(
+                    "Completed in {:.2}s",
+                    self.started.elapsed().as_secs_f64()
+                ));
+                self.log.finish_and_clear();
+            }
+            Event::PhaseStarted { text, .. } => self.bar.set_message(text.clone()),
+            Event::PhaseProgress { phase, percent } => {
+                self.log.set_message(format!("{phase}: {percent}%"))
+            }
+            Event::PhaseCompleted { .. } => {}
+            Event::BranchStarted { branch, text } => self.start_branch(branch, text),
+            Event::BranchFinished {
+                branch,
+                result,
+                text,
+            } => self.finish_branch(branch, result, text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hammer-progress-sink-{}-{name}", process::id()))
+    }
+
+    fn styled(style: Option<&str>, chars: Option<&str>) -> Result<(), String> {
+        build_style(&Options {
+            style: style.map(str::to_string),
+            chars: chars.map(str::to_string),
+            ..Options::default()
+        })
+        .map(|_| ())
+    }
+
+    #[test]
+    fn default_and_wide_templates_are_valid() {
+        assert!(validate_placeholders(DEFAULT_TEMPLATE).is_ok());
+        assert!(validate_placeholders(WIDE_TEMPLATE).is_ok());
+        assert!(styled(None, None).is_ok());
+        assert!(build_style(&Options {
+            width: Some(60),
+            ..Options::default()
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn placeholders_with_formats_and_escaped_braces_pass() {
+        assert!(validate_placeholders("{bar:20.green/red} {{literal}} {pos}/{len} {msg}").is_ok());
+        assert!(styled(Some("{wide_bar} {percent}%"), None).is_ok());
+    }
+
+    #[test]
+    fn unknown_placeholder_lists_the_allowed_ones() {
+        let err = styled(Some("{bar} {speed}"), None).unwrap_err();
+        assert!(err.contains("Unknown placeholder '{speed}'"), "{err}");
+        assert!(err.contains("Allowed placeholders: bar, wide_bar"), "{err}");
+    }
+
+    #[test]
+    fn unclosed_placeholder_is_an_error_not_a_panic() {
+        let err = styled(Some("{bar"), None).unwrap_err();
+        assert!(err.contains("Unclosed placeholder"), "{err}");
+    }
+
+    #[test]
+    fn chars_need_two_of_the_same_width() {
+        assert!(styled(None, Some("#>-")).is_ok());
+        assert!(styled(None, Some("#"))
+            .unwrap_err()
+            .contains("at least 2 characters"));
+        assert!(styled(None, Some("#界"))
+            .unwrap_err()
+            .contains("same display width"));
+    }
+
+    #[test]
+    fn output_term_writes_escape_sequences() {
+        let path = scratch("term");
+        let term = OutputTerm {
+            out: Mutex::new(File::create(&path).unwrap()),
+            width: 42,
+        };
+        assert_eq!(term.width(), 42);
+        term.move_cursor_up(0).unwrap();
+        term.move_cursor_up(2).unwrap();
+        term.clear_line().unwrap();
+        term.write_line("bar").unwrap();
+        term.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "\x1b[2A\r\x1b[2Kbar\n");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn output_to_an_unwritable_path_is_an_error() {
+        let err = build_target(&Options {
+            output: Some("/nonexistent-dir/bar".to_string()),
+            ..Options::default()
+        })
+        .err()
+        .unwrap();
+        assert!(
+            err.starts_with("Cannot open output /nonexistent-dir/bar"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn sink_draws_messages_logs_and_the_finish_into_output() {
+        let path = scratch("sink");
+        let opts = Options {
+            width: Some(100),
+            output: Some(path.to_string_lossy().into_owned()),
+            ..Options::default()
+        };
+        {
+            let mut sink = IndicatifSink::new(&opts).unwrap();
+            sink.handle(&Event::SetTotal(2));
+            sink.handle(&Event::Msg("Fetching packages".to_string()));
+            sink.handle(&Event::Update);
+            sink.handle(&Event::Log("Get:1 example".to_string()));
+            sink.bar.tick();
+            sink.log.tick();
+            sink.handle(&Event::Update);
+            assert_eq!(sink.bar.position(), 2);
+            assert_eq!(sink.bar.length(), Some(2));
+            sink.handle(&Event::Done);
+            assert!(sink.bar.is_finished());
+        }
+        let drawn = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(drawn.contains("Fetching packages"), "{drawn}");
+        assert!(!drawn.contains("✓"), "{drawn}");
+        assert!(drawn.contains("Log: Get:1 example"), "{drawn}");
+        assert!(drawn.contains("Completed in"), "{drawn}");
+    }
+
+    #[test]
+    fn branches_get_a_line_each_between_bar_and_log() {
+        let path = scratch("branches");
+        let opts = Options {
+            width: Some(100),
+            output: Some(path.to_string_lossy().into_owned()),
+            ..Options::default()
+        };
+        {
+            let mut sink = IndicatifSink::new(&opts).unwrap();
+            sink.handle(&Event::Msg("Preparing".to_string()));
+            for branch in ["snapshot", "apt lists"] {
+                sink.handle(&Event::BranchStarted {
+                    branch: branch.to_string(),
+                    text: String::new(),
+                });
+            }
+            assert_eq!(sink.branches.len(), 2);
+            assert!(sink.branches.iter().all(|(_, line)| !line.is_finished()));
+            // Started twice still draws one line
+            sink.handle(&Event::BranchStarted {
+                branch: "snapshot".to_string(),
+                text: "btrfs snapshot".to_string(),
+            });
+            assert_eq!(sink.branches.len(), 2);
+            sink.handle(&Event::BranchFinished {
+                branch: "snapshot".to_string(),
+                result: "done".to_string(),
+                text: "in 1.5s".to_string(),
+            });
+            sink.handle(&Event::BranchFinished {
+                branch: "apt lists".to_string(),
+                result: "failed".to_string(),
+                text: "no network".to_string(),
+            });
+            // A finish without a start still shows
+            sink.handle(&Event::BranchFinished {
+                branch: "orphan".to_string(),
+                result: "done".to_string(),
+                text: String::new(),
+            });
+            sink.handle(&Event::BranchStarted {
+                branch: "left".to_string(),
+                text: String::new(),
+            });
+            sink.handle(&Event::Done);
+            assert!(sink.branches.iter().all(|(_, line)| line.is_finished()));
+        }
+        let drawn = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(drawn.contains("✓ snapshot: done in 1.5s"), "{drawn}");
+        assert!(drawn.contains("✗ apt lists: failed no network"), "{drawn}");
+        assert!(drawn.contains("✓ orphan: done"), "{drawn}");
+    }
+}
@@ -0,0 +1,322 @@
+//! The machine-readable record of a run, for `--summary-file`.
+//!
+//! A [`Summary`] watches the same events as the sink and, when the renderer
+//! stops, is written as one JSON object:
+//!
+//! ```json
+//! {"termination":"success","result":"staged","total_steps":4,"completed_steps":4,
+//!  "elapsed_seconds":12.5,"phases":[{"message":"Running apt","seconds":9.75}]}
+//! ```
+//!
+//! A phase lasts from one `msg` (or `phase_started`) to the next one with a
+//! different text. `termination` is `success` or `failure` when an event ended
+//! the run and `interrupted` when stdin closed first; `result` is the
+//! `operation_result` of the run, absent after a plain `done`.
+
+use crate::event::{json_string, Event};
+use std::fmt::Write as _;
+use std::fs;
+use std::process;
+use std::time::{Duration, Instant};
+
+/// Results of `operation_result` that mean the operation did not go through.
+const FAILED_RESULTS: &[&str] = &["failed", "cancelled"];
+
+/// Why the renderer stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Success,
+    Failure,
+    /// stdin ended before `done` or `operation_result`.
+    Interrupted,
+}
+
+impl Termination {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Termination::Success => "success",
+            Termination::Failure => "failure",
+            Termination::Interrupted => "interrupted",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Summary {
+    started: Instant,
+    total: u64,
+    completed: u64,
+    phases: Vec<(String, Duration)>,
+    current: Option<(String, Instant)>,
+    result: Option<String>,
+    termination: Option<Termination>,
+}
+
+impl Default for Summary {
+    fn default() -> Summary {
+        Summary::new()
+    }
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary {
+            started: Instant::now(),
+            total: 0,
+            completed: 0,
+            phases: Vec::new(),
+            current: None,
+            result: None,
+            termination: None,
+        }
+    }
+
+    pub fn observe(&mut self, event: &Event) {
+        if self.termination.is_some() {
+            return;
+        }
+        match event {
+            Event::SetTotal(total) => self.total = *total,
+            Event::Update => self.completed += 1,
+            Event::Msg(text) | Event::PhaseStarted { text, .. } => self.enter(text),
+            Event::Done => self.end(Termination::Success),
+            Event::OperationResult { result, .. } => {
+                self.result = Some(result.clone());
+                self.end(if FAILED_RESULTS.contains(&result.as_str()) {
+                    Termination::Failure
+                } else {
+                    Termination::Success
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// How the run ended, `None` while it goes on.
+    pub fn termination(&self) -> Option<Termination> {
+        self.termination
+    }
+
+    /// Ends a run whose input stopped without a final event.
+    pub fn interrupt(&mut self) {
+        if self.termination.is_none() {
+            self.end(Termination::Interrupted);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let termination = self.termination.unwrap_or(Termination::Interrupted);
+        let mut out = format!("{{\"termination\":\"{}\"", termination.as_str());
+        if let Some(result) = &self.result {
+            let _ = write!(out, ",\"result\":{}", json_string(result));
+        }
+        let _ = write!(
+            out,
+            ",\"total_steps\":{},\"completed_steps\":{},\"elapsed_seconds\":{:.3},\"phases\":[",
+            self.total,
+            self.completed,
+            self.started.elapsed().as_secs_f64()
+        );
+        for (i, (message, span)) in self.phases.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(
+                out,
+                "{{\"message\":{},\"seconds\":{:.3}}}",
+                json_string(message),
+                span.as_secs_f64()
+            );
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// Writes the summary to path through a temporary file, so readers never see half of it.
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let tmp = format!("{path}.tmp.{}", process::id());
+        fs::write(&tmp, self.to_json() + "\n")
+            .and_then(|()| fs::rename(&tmp, path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&tmp);
+                format!("Cannot write summary {path}: {e}")
+            })
+    }
+
+    fn enter(&mut self, text: &str) {
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|(current, _)| current == text)
+        {
+            return;
+        }
+        self.close_phase();
+        self.current = Some((text.to_string(), Instant::now()));
+    }
+
+    fn close_phase(&mut self) {
+        if let Some((message, since)) = self.current.take() {
+            self.phases.push((message, since.elapsed()));
+        }
+    }
+
+    fn end(&mut self, termination: Termination) {
+        self.close_phase();
+        self.termination = Some(termination);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hammer-progress-summary-{}-{name}", process::id()))
+    }
+
+    fn msg(text: &str) -> Event {
+        Event::Msg(text.to_string())
+    }
+
+    fn phase_names(summary: &Summary) -> Vec<&str> {
+        summary.phases.iter().map(|(m, _)| m.as_str()).collect()
+    }
+
+    #[test]
+    fn phases_change_with_the_message_text() {
+        let mut summary = Summary::new();
+        for event in [
+            msg("Fetching"),
+            msg("Fetching"),
+            Event::PhaseStarted {
+                phase: "apt".to_string(),
+                text: "Running apt".to_string(),
+            },
+            Event::Log("not a phase".to_string()),
+            msg("Fetching"),
+            Event::Done,
+        ] {
+            summary.observe(&event);
+        }
+        assert_eq!(
+            phase_names(&summary),
+            ["Fetching", "Running apt", "Fetching"]
+        );
+        assert!(summary.current.is_none());
+    }
+
+    #[test]
+    fn steps_are_counted() {
+        let mut summary = Summary::new();
+        for event in [Event::SetTotal(3), Event::Update, Event::Update] {
+            summary.observe(&event);
+        }
+        summary.interrupt();
+        let json = summary.to_json();
+        assert!(
+            json.contains("\"total_steps\":3,\"completed_steps\":2,"),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn done_is_a_success_without_result() {
+        let mut summary = Summary::new();
+        summary.observe(&Event::Done);
+        assert_eq!(summary.termination(), Some(Termination::Success));
+        assert!(!summary.to_json().contains("\"result\""));
+    }
+
+    #[test]
+    fn operation_results_decide_success_or_failure() {
+        for (result, termination) in [
+            ("success", Termination::Success),
+            ("staged", Termination::Success),
+            ("failed", Termination::Failure),
+            ("cancelled", Termination::Failure),
+        ] {
+            let mut summary = Summary::new();
+            summary.observe(&Event::OperationResult {
+                result: result.to_string(),
+                deployment: None,
+            });
+            assert_eq!(summary.termination(), Some(termination), "{result}");
+            assert!(summary
+                .to_json()
+                .contains(&format!("\"result\":\"{result}\"")));
+        }
+    }
+
+    #[test]
+    fn nothing_counts_after_the_end() {
+        let mut summary = Summary::new();
+        summary.observe(&msg("Fetching"));
+        summary.observe(&Event::Done);
+        summary.observe(&Event::Update);
+        summary.observe(&msg("Late"));
+        summary.observe(&Event::OperationResult {
+            result: "failed".to_string(),
+            deployment: None,
+        });
+        summary.interrupt();
+        assert_eq!(summary.termination(), Some(Termination::Success));
+        assert_eq!(summary.completed, 0);
+        assert_eq!(phase_names(&summary), ["Fetching"]);
+    }
+
+    #[test]
+    fn input_ending_early_is_interrupted() {
+        let mut summary = Summary::new();
+        summary.observe(&msg("Fetching"));
+        summary.interrupt();
+        assert_eq!(summary.termination(), Some(Termination::Interrupted));
+        assert_eq!(phase_names(&summary), ["Fetching"]);
+        assert!(summary
+            .to_json()
+            .starts_with("{\"termination\":\"interrupted\""));
+    }
+
+    #[test]
+    fn json_escapes_messages() {
+        let mut summary = Summary::new();
+        summary.observe(&msg("say \"hi\"\n"));
+        summary.interrupt();
+        let json = summary.to_json();
+        assert!(
+            json.contains("{\"message\":\"say \\\"hi\\\"\\n\",\"seconds\":"),
+            "{json}"
+        );
+        assert!(json.ends_with("]}"));
+    }
+
+    #[test]
+    fn write_replaces_the_file_and_leaves_no_temporary() {
+        let path = scratch("write");
+        fs::write(&path, "old contents").unwrap();
+        let mut summary = Summary::new();
+        summary.observe(&Event::Done);
+        summary.write(path.to_str().unwrap()).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(
+            written.starts_with("{\"termination\":\"success\""),
+            "{written}"
+        );
+        assert!(written.ends_with("]}\n"));
+        assert!(!PathBuf::from(format!("{}.tmp.{}", path.display(), process::id())).exists());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn failed_write_reports_the_path_and_cleans_up() {
+        let dir = scratch("missing-dir");
+        let path = dir.join("summary.json");
+        let err = Summary::new().write(path.to_str().unwrap()).unwrap_err();
+        assert!(
+            err.starts_with(&format!("Cannot write summary {}", path.display())),
+            "{err}"
+        );
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,181 @@
+//! Runs the binary with each drawing option and checks what it writes.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+const EVENTS: &str = "set_total 2\nmsg Unpacking\nupdate\nupdate\ndone\n";
+
+fn scratch(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("hammer-progress-cli-{}-{name}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn run(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hammer-progress-bar"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("binary runs");
+    // Exits on bad options before reading, so a broken pipe is expected then
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// Runs with --output into a scratch file and returns what was drawn there.
+fn drawn(name: &str, args: &[&str]) -> String {
+    let path = scratch(name);
+    let path_arg = path.to_string_lossy().into_owned();
+    let mut all = vec!["--output", path_arg.as_str()];
+    all.extend_from_slice(args);
+    let output = run(&all, EVENTS);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let text = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    text
+}
+
+#[test]
+fn help_prints_the_usage() {
+    let output = run(&["--help"], "");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage: hammer-progress-bar"));
+}
+
+#[test]
+fn without_output_it_draws_nothing_when_stderr_is_no_terminal() {
+    let output = run(&[], EVENTS);
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn output_gets_the_redraws() {
+    let text = drawn("plain", &[]);
+    assert!(text.contains("Completed in"), "{text}");
+    assert!(text.contains("2/2"), "{text}");
+}
+
+/// The filled cells of the last bar drawn.
+fn filled(text: &str) -> usize {
+    let last = text
+        .split(['\n', '\r'])
+        .rfind(|line| line.contains("Completed in"))
+        .unwrap_or_else(|| panic!("no finished bar in {text:?}"));
+    last.matches('█').count()
+}
+
+#[test]
+fn width_sizes_the_wide_bar() {
+    let narrow = drawn("narrow", &["--width", "80"]);
+    let wide = drawn("wide", &["--width", "120"]);
+    assert_eq!(filled(&wide) - filled(&narrow), 40);
+    for line in wide
+        .split(['\n', '\r'])
+        .filter(|line| line.contains("Completed in"))
+    {
+        let visible = console::strip_ansi_codes(line);
+        assert!(
+            console::measure_text_width(visible.trim_end()) <= 120,
+            "too wide: {visible:?}"
+        );
+    }
+}
+
+#[test]
+fn style_and_chars_shape_the_bar() {
+    let text = drawn(
+        "style",
+        &["--style", "[{bar:10}] {pos}/{len} {msg}", "--chars", "#-"],
+    );
+    assert!(text.contains("[##########] 2/2 Completed in"), "{text}");
+}
+
+#[test]
+fn inline_values_work_like_separate_ones() {
+    let text = drawn("inline", &["--style=<{bar:4}>", "--chars=*.", "--width=30"]);
+    assert!(text.contains("<****>"), "{text}");
+}
+
+#[test]
+fn output_can_be_a_fifo() {
+    let path = scratch("fifo");
+    let status = Command::new("mkfifo")
+        .arg(&path)
+        .status()
+        .expect("mkfifo runs");
+    assert!(status.success());
+    let reader_path = path.clone();
+    let reader = thread::spawn(move || {
+        let mut text = String::new();
+        fs::File::open(reader_path)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    });
+    let path_arg = path.to_string_lossy().into_owned();
+    let output = run(&["--output", &path_arg, "--width", "60"], EVENTS);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let text = reader.join().unwrap();
+    let _ = fs::remove_file(&path);
+    assert!(text.contains("Completed in"), "{text}");
+}
+
+#[test]
+fn invalid_template_lists_the_placeholders() {
+    let output = run(&["--style", "{bar} {nope}"], "");
+    assert_eq!(output.status.code(), Some(2));
+    let err = stderr(&output);
+    assert!(
+        err.contains("Invalid --style template: Unknown placeholder '{nope}'"),
+        "{err}"
+    );
+    assert!(err.contains("Allowed placeholders: bar, wide_bar"), "{err}");
+    assert!(!err.contains("panicked"), "{err}");
+}
+
+#[test]
+fn invalid_chars_are_refused() {
+    let output = run(&["--chars", "x"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Invalid --chars 'x'"));
+}
+
+#[test]
+fn invalid_width_is_refused() {
+    for width in ["0", "wide", "-3"] {
+        let output = run(&["--width", width], "");
+        assert_eq!(output.status.code(), Some(2), "--width {width}");
+        assert!(stderr(&output).contains(&format!("Invalid width '{width}'")));
+    }
+}
+
+#[test]
+fn missing_values_and_unknown_flags_print_the_usage() {
+    let output = run(&["--output"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Missing value for --output"));
+    assert!(stderr(&output).contains("Usage: hammer-progress-bar"));
+
+    let output = run(&["--colour", "red"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Unknown option: --colour"));
+}
+
+#[test]
+fn unopenable_output_is_an_error() {
+    let output = run(&["--output", "/nonexistent-dir/bar"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Cannot open output /nonexistent-dir/bar"));
+}
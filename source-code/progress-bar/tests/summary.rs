@@ -0,0 +1,182 @@
+//! Drives the binary through each way a run ends and checks the --summary-file it leaves.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn scratch(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "hammer-progress-summary-{}-{name}",
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn spawn(summary: &PathBuf) -> (Child, ChildStdin) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hammer-progress-bar"))
+        .arg("--summary-file")
+        .arg(summary)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("binary runs");
+    let stdin = child.stdin.take().unwrap();
+    (child, stdin)
+}
+
+/// Waits for the child without closing its stdin, failing after a few seconds.
+fn wait(child: &mut Child) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status.success();
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let _ = child.kill();
+    panic!("renderer did not stop");
+}
+
+fn read(path: &PathBuf) -> String {
+    let text = fs::read_to_string(path).expect("summary was written");
+    let _ = fs::remove_file(path);
+    assert!(text.ends_with("}\n"), "{text}");
+    text
+}
+
+/// The number after "key": in a flat JSON document.
+fn number(json: &str, key: &str) -> f64 {
+    let start = json
+        .find(&format!("\"{key}\":"))
+        .unwrap_or_else(|| panic!("no {key} in {json}"))
+        + key.len()
+        + 3;
+    let end = json[start..]
+        .find(|c: char| c != '.' && !c.is_ascii_digit())
+        .unwrap()
+        + start;
+    json[start..end].parse().unwrap()
+}
+
+#[test]
+fn clean_finish() {
+    let path = scratch("success");
+    let (mut child, mut stdin) = spawn(&path);
+    writeln!(stdin, "set_total 3\nmsg Fetching").unwrap();
+    thread::sleep(Duration::from_millis(300));
+    writeln!(
+        stdin,
+        "update\n{{\"event\":\"msg\",\"text\":\"Unpacking\"}}\nupdate\nupdate\ndone"
+    )
+    .unwrap();
+    // done ends the run even while stdin stays open
+    assert!(wait(&mut child));
+    drop(stdin);
+    let json = read(&path);
+    assert!(
+        json.starts_with("{\"termination\":\"success\",\"total_steps\":3,\"completed_steps\":3,"),
+        "{json}"
+    );
+    assert!(!json.contains("\"result\""), "{json}");
+    assert!(
+        json.contains("\"phases\":[{\"message\":\"Fetching\",\"seconds\":"),
+        "{json}"
+    );
+    assert!(
+        json.contains(",{\"message\":\"Unpacking\",\"seconds\":"),
+        "{json}"
+    );
+    assert!(number(&json, "seconds") >= 0.25, "{json}");
+    assert!(number(&json, "elapsed_seconds") >= 0.25, "{json}");
+}
+
+#[test]
+fn failure() {
+    let path = scratch("failure");
+    let (mut child, mut stdin) = spawn(&path);
+    writeln!(
+        stdin,
+        "set_total 4\nphase_started apt Running apt\nupdate\nerror dpkg failed\noperation_result failed hammer-20240101120000"
+    )
+    .unwrap();
+    assert!(wait(&mut child));
+    drop(stdin);
+    let json = read(&path);
+    assert!(json.starts_with("{\"termination\":\"failure\",\"result\":\"failed\",\"total_steps\":4,\"completed_steps\":1,"), "{json}");
+    assert!(
+        json.contains("\"phases\":[{\"message\":\"Running apt\",\"seconds\":"),
+        "{json}"
+    );
+}
+
+#[test]
+fn staged_result_is_a_success() {
+    let path = scratch("staged");
+    let (mut child, mut stdin) = spawn(&path);
+    writeln!(
+        stdin,
+        "{{\"event\":\"operation_result\",\"result\":\"staged\"}}"
+    )
+    .unwrap();
+    assert!(wait(&mut child));
+    let json = read(&path);
+    assert!(
+        json.starts_with("{\"termination\":\"success\",\"result\":\"staged\","),
+        "{json}"
+    );
+}
+
+#[test]
+fn input_closing_early() {
+    let path = scratch("interrupted");
+    let (mut child, mut stdin) = spawn(&path);
+    writeln!(stdin, "set_total 5\nmsg Fetching\nupdate\nnot an event").unwrap();
+    drop(stdin);
+    assert!(wait(&mut child));
+    let json = read(&path);
+    assert!(
+        json.starts_with(
+            "{\"termination\":\"interrupted\",\"total_steps\":5,\"completed_steps\":1,"
+        ),
+        "{json}"
+    );
+    assert!(
+        json.contains("[{\"message\":\"Fetching\",\"seconds\":"),
+        "{json}"
+    );
+}
+
+#[test]
+fn empty_input() {
+    let path = scratch("empty");
+    let (mut child, stdin) = spawn(&path);
+    drop(stdin);
+    assert!(wait(&mut child));
+    let json = read(&path);
+    assert!(
+        json.starts_with(
+            "{\"termination\":\"interrupted\",\"total_steps\":0,\"completed_steps\":0,"
+        ),
+        "{json}"
+    );
+    assert!(json.ends_with("\"phases\":[]}\n"), "{json}");
+}
+
+#[test]
+fn unwritable_summary_fails_the_run() {
+    let path = PathBuf::from("/nonexistent-dir/summary.json");
+    let (mut child, mut stdin) = spawn(&path);
+    writeln!(stdin, "done").unwrap();
+    assert!(!wait(&mut child));
+    let mut err = String::new();
+    std::io::Read::read_to_string(&mut child.stderr.take().unwrap(), &mut err).unwrap();
+    assert!(
+        err.contains("Cannot write summary /nonexistent-dir/summary.json"),
+        "{err}"
+    );
+}
@@ -58,7 +58,7 @@ func initialModel() model {
 		item{title: "Install package", desc: "Install a package (atomic optional)", command: "install", hasPackage: true, hasAtomic: true},
 		item{title: "Remove package", desc: "Remove a package (atomic optional)", command: "remove", hasPackage: true, hasAtomic: true},
 		item{title: "Update", desc: "Update the system atomically", command: "update", hasPackage: false, hasAtomic: false},
-		item{title: "Clean", desc: "Clean up unused resources", command: "clean", hasPackage: false, hasAtomic: false},
+		item{title: "Clean", desc: "Clean up unused resources", command: "clean all", hasPackage: false, hasAtomic: false},
 		item{title: "Refresh", desc: "Refresh repositories", command: "refresh", hasPackage: false, hasAtomic: false},
 		item{title: "Switch", desc: "Switch to a deployment (rollback if no arg)", command: "switch", hasPackage: false, hasAtomic: false},
 		item{title: "Deploy", desc: "Create a new deployment", command: "deploy", hasPackage: false, hasAtomic: false},